@@ -4,20 +4,66 @@
 
 #[macro_use]
 extern crate log;
+extern crate filetime;
 
+use std::collections::HashMap;
 use std::io;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
-// TODO macro this block for portability
+#[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 
+#[cfg(windows)]
+use std::os::windows::fs::MetadataExt;
+
+/// A value that identifies a file or directory independent of its path,
+/// used to detect the copy's own root mid-walk and, when enabled, to spot
+/// multiple hard links to the same file.
+///
+/// On Unix this is the `(dev, ino)` pair. On Windows it's the volume serial
+/// number and file index, when the platform makes them available to us;
+/// `None` otherwise, meaning identity can't be determined for that entry.
+#[cfg(unix)]
 type UniqueId = (u64, u64);
 
-fn new_os_file<P: AsRef<Path>>(path: P) -> Box<OsFile<UniqueId=UniqueId>> {
-    Box::new(LunixFile { path: path.as_ref().to_path_buf() })
+#[cfg(windows)]
+type UniqueId = Option<(u64, u64)>;
+
+#[cfg(unix)]
+fn unique_id_from_metadata(metadata: &fs::Metadata) -> UniqueId {
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(windows)]
+fn unique_id_from_metadata(metadata: &fs::Metadata) -> UniqueId {
+    match (metadata.volume_serial_number(), metadata.file_index()) {
+        (Some(volume), Some(index)) => Some((u64::from(volume), index)),
+        _ => None,
+    }
+}
+
+#[cfg(unix)]
+fn unique_id_is_known(_id: &UniqueId) -> bool {
+    true
+}
+
+#[cfg(windows)]
+fn unique_id_is_known(id: &UniqueId) -> bool {
+    id.is_some()
+}
+
+#[cfg(unix)]
+fn new_os_file<P: AsRef<Path>>(path: P) -> UnixFile {
+    UnixFile::from_path(path.as_ref().to_path_buf())
+}
+
+#[cfg(windows)]
+fn new_os_file<P: AsRef<Path>>(path: P) -> WindowsFile {
+    WindowsFile::from_path(path.as_ref().to_path_buf())
 }
-// TODO macro above
 
 #[derive(Debug)]
 pub enum Error {
@@ -30,6 +76,10 @@ pub enum Error {
         source: PathBuf,
         destination: PathBuf
     },
+    DestinationInsideSource {
+        source: PathBuf,
+        destination: PathBuf
+    },
     Unknown(PathBuf),
     Io(io::Error),
 }
@@ -42,7 +92,147 @@ impl From<io::Error> for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// This can be used to specify the error reporting behavior of the 
+/// Controls what happens when a destination entry already exists, for
+/// merging a copy into a previously-populated directory. See
+/// `CopyOptions::overwrite`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Merge into an existing destination, but never replace an existing
+    /// destination file: it is left as it is and the source file skipped.
+    Never,
+
+    /// Replace an existing destination file unconditionally.
+    Always,
+
+    /// Replace an existing destination file only if the source's
+    /// modification time is newer than the destination's.
+    IfNewer,
+}
+
+/// A boxed predicate deciding whether a given entry should be copied; see
+/// `CopyOptions::filter`. Named so `CopyOptions` doesn't have to spell out
+/// the trait object type inline.
+type Filter<'a> = Box<FnMut(&Path, &fs::Metadata) -> bool + 'a>;
+
+/// Controls the details of a copy operation: which entries get copied and
+/// what metadata is carried over to the destination.
+///
+/// Build one with `CopyOptions::new()` and the fluent setters below, then
+/// pass it to `copy_dir_with_options`.
+pub struct CopyOptions<'a> {
+    filter: Option<Filter<'a>>,
+    copy_permissions: bool,
+    copy_mtimes: bool,
+    parallel: Option<usize>,
+    preserve_hard_links: bool,
+    overwrite: Option<Overwrite>,
+}
+
+impl<'a> Default for CopyOptions<'a> {
+    fn default() -> Self {
+        CopyOptions::new()
+    }
+}
+
+impl<'a> CopyOptions<'a> {
+    /// Create a new `CopyOptions` with the default behavior: every entry is
+    /// copied, permissions are preserved, mtimes are not, the copy is
+    /// sequential, hard links are preserved, and an existing destination
+    /// is rejected rather than merged into.
+    pub fn new() -> Self {
+        CopyOptions {
+            filter: None,
+            copy_permissions: true,
+            copy_mtimes: false,
+            parallel: None,
+            preserve_hard_links: true,
+            overwrite: None,
+        }
+    }
+
+    /// Only entries for which `filter` returns `true` will be copied. When
+    /// a directory is filtered out, its contents are skipped as well.
+    pub fn filter<F>(mut self, filter: F) -> Self
+        where F: FnMut(&Path, &fs::Metadata) -> bool + 'a {
+
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Whether to apply the source entry's permissions to the destination
+    /// entry. Defaults to `true`.
+    pub fn copy_permissions(mut self, copy_permissions: bool) -> Self {
+        self.copy_permissions = copy_permissions;
+        self
+    }
+
+    /// Whether to apply the source file's modification time to each copied
+    /// regular file. Directory mtimes are not set. Defaults to `false`.
+    pub fn copy_mtimes(mut self, copy_mtimes: bool) -> Self {
+        self.copy_mtimes = copy_mtimes;
+        self
+    }
+
+    /// Copy regular files using `n` worker threads (at least one, even
+    /// when `n` is 0) instead of the default
+    /// single-threaded depth-first walk. The directory skeleton is still
+    /// created, and directory permissions still applied, sequentially;
+    /// only the independent file copies are parallelized.
+    pub fn parallel(mut self, n: usize) -> Self {
+        self.parallel = Some(n);
+        self
+    }
+
+    /// Whether multiple hard links to the same inode should be re-linked
+    /// at the destination, rather than each being copied independently.
+    /// Defaults to `true`.
+    pub fn preserve_hard_links(mut self, preserve_hard_links: bool) -> Self {
+        self.preserve_hard_links = preserve_hard_links;
+        self
+    }
+
+    /// Merge into an existing destination directory instead of rejecting
+    /// it outright, resolving per-file conflicts according to `mode`. By
+    /// default (this method not called) an existing destination is always
+    /// rejected with `Error::DestinationExists`.
+    pub fn overwrite(mut self, mode: Overwrite) -> Self {
+        self.overwrite = Some(mode);
+        self
+    }
+
+    fn should_copy(&mut self, path: &Path, metadata: &fs::Metadata) -> bool {
+        match self.filter {
+            Some(ref mut filter) => filter(path, metadata),
+            None => true,
+        }
+    }
+}
+
+/// A summary of the work done by a copy operation, accumulated as the
+/// recursive walk proceeds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CopyStats {
+    /// Number of regular files copied.
+    pub files: usize,
+
+    /// Number of source directories copied, each either created at the
+    /// destination or, when overwriting, merged into an existing one.
+    pub dirs: usize,
+
+    /// Total number of bytes copied, as reported by `std::fs::copy`.
+    pub bytes: u64,
+
+    /// Number of symbolic links copied.
+    pub symlinks: usize,
+}
+
+impl CopyStats {
+    fn new() -> Self {
+        CopyStats::default()
+    }
+}
+
+/// This can be used to specify the error reporting behavior of the
 /// `copy_dir_with_handler` function.
 #[derive(Debug)]
 pub enum Handler {
@@ -79,53 +269,171 @@ macro_rules! handle {
     }
 }
 
-trait OsFile {
-    type UniqueId;
+/// Whether a file should be (re)written to `destination`, given the
+/// configured overwrite mode. Shared by the sequential (`OsFile::copy`)
+/// and parallel (`walk_for_parallel_copy`) walks so their merge semantics
+/// can't drift apart.
+fn should_write_file(destination: &Path,
+                      source_metadata: &fs::Metadata,
+                      overwrite: Option<Overwrite>) -> bool {
+
+    let destination_exists = destination.exists();
+
+    match overwrite {
+        Some(Overwrite::Always) => true,
+        Some(Overwrite::IfNewer) => !destination_exists || {
+            let source_mtime =
+                filetime::FileTime::from_last_modification_time(source_metadata);
+
+            match fs::metadata(destination) {
+                Ok(dest_metadata) => source_mtime >
+                    filetime::FileTime::from_last_modification_time(&dest_metadata),
+                Err(_) => true,
+            }
+        },
+        Some(Overwrite::Never) | None => !destination_exists,
+    }
+}
 
-    fn path(&self) -> &Path;
-    fn unique_id(&self) -> Result<UniqueId>;
-    fn copy(&self,
-            destination: &Path,
-            root_destination: Option<Self::UniqueId>,
-            error_handler: &mut Handler);
+/// What to do about a file entry given its hard-link identity: either it's
+/// the first file this walk has seen with `unique_id` and must actually be
+/// copied, or a later one that should become a hard link to the first
+/// file's destination instead of an independent copy.
+enum HardLinkPlan {
+    Copy,
+    LinkTo(PathBuf),
+}
 
-    fn metadata(&self) -> Result<std::fs::Metadata> {
-        std::fs::metadata(&self.path())
-            .map_err( |err| Error::from(err) )
+/// Decide `destination`'s `HardLinkPlan`, recording it as the first file
+/// claiming `unique_id` in `hard_links` if it is one. `eligible` folds in
+/// whether hard-link preservation is even enabled for this entry (not a
+/// symlink, has a known identity, etc). Shared by the sequential and
+/// parallel walks.
+fn plan_hard_link(destination: &Path,
+                   unique_id: UniqueId,
+                   eligible: bool,
+                   hard_links: &mut HashMap<UniqueId, PathBuf>) -> HardLinkPlan {
+
+    if !eligible {
+        return HardLinkPlan::Copy;
+    }
+
+    if let Some(existing) = hard_links.get(&unique_id).cloned() {
+        HardLinkPlan::LinkTo(existing)
+    } else {
+        hard_links.insert(unique_id, destination.to_path_buf());
+        HardLinkPlan::Copy
     }
 }
 
-struct LunixFile {
-    path: PathBuf,
+/// Hard-link `destination` to the first file that claimed `unique_id`,
+/// falling back to `Copy` (handled by the caller) if that fails. Only
+/// safe to call once the first file's destination actually exists on
+/// disk, which is true by construction in the sequential walk (`OsFile`
+/// copies an entry before moving on to its siblings). Returns whether
+/// `destination` was hard-linked.
+fn resolve_hard_link(destination: &Path,
+                      unique_id: UniqueId,
+                      eligible: bool,
+                      hard_links: &mut HashMap<UniqueId, PathBuf>) -> bool {
+
+    match plan_hard_link(destination, unique_id, eligible, hard_links) {
+        HardLinkPlan::Copy => false,
+        HardLinkPlan::LinkTo(existing) => fs::hard_link(&existing, destination).is_ok(),
+    }
 }
 
-impl OsFile for LunixFile {
-    type UniqueId = (u64, u64); // dev and inode
+trait OsFile: Sized {
+    fn from_path(path: PathBuf) -> Self;
+    fn path(&self) -> &Path;
 
-    fn path(&self) -> &Path {
-        self.path.as_ref()
+    fn unique_id(&self) -> Result<UniqueId> {
+        let metadata = self.metadata()?;
+        Ok(unique_id_from_metadata(&metadata))
     }
 
-    // TODO macro in different variants here for linux/unix
-    fn unique_id(&self) -> Result<Self::UniqueId> {
-        let metadata = self.metadata()?;
-        Ok((metadata.dev(), metadata.ino()))
+    /// Whether `id` actually identifies this file, as opposed to being an
+    /// "unknown" sentinel on platforms where identity can't always be
+    /// determined (see `unique_id_from_metadata`).
+    fn has_known_unique_id(&self, id: &UniqueId) -> bool {
+        unique_id_is_known(id)
+    }
+
+    fn metadata(&self) -> Result<std::fs::Metadata> {
+        std::fs::metadata(&self.path())
+            .map_err( |err| Error::from(err) )
     }
 
     fn copy(&self,
             destination: &Path,
             mut root_destination: Option<UniqueId>,
+            options: &mut CopyOptions,
+            stats: &mut CopyStats,
+            hard_links: &mut HashMap<UniqueId, PathBuf>,
             handler: &mut Handler) {
 
         let unique_id = handle!(handler, self.unique_id());
         let metadata = handle!(handler, self.metadata());
 
+        if !options.should_copy(self.path(), &metadata) {
+            return
+        }
+
         if metadata.is_file() {
-            handle!(
-                handler,
-                fs::copy(&self.path, destination).map( |_| () )
-                    .map_err( |err| Error::from(err) )
-            )
+            let is_symlink = fs::symlink_metadata(self.path())
+                .map( |m| m.file_type().is_symlink() )
+                .unwrap_or(false);
+
+            let destination_exists = destination.exists();
+
+            if !should_write_file(destination, &metadata, options.overwrite) {
+                return
+            }
+
+            if destination_exists {
+                handle!(handler, fs::remove_file(destination));
+            }
+
+            let hard_linked = resolve_hard_link(
+                destination,
+                unique_id,
+                options.preserve_hard_links && !is_symlink
+                    && self.has_known_unique_id(&unique_id),
+                hard_links,
+            );
+
+            if hard_linked {
+                stats.files += 1;
+
+            } else {
+                let bytes = handle!(
+                    handler,
+                    fs::copy(self.path(), destination)
+                        .map_err( |err| Error::from(err) )
+                );
+                stats.bytes += bytes;
+
+                if is_symlink {
+                    stats.symlinks += 1;
+                } else {
+                    stats.files += 1;
+                }
+
+                if options.copy_permissions {
+                    handle!(
+                        handler,
+                        fs::set_permissions(destination, metadata.permissions())
+                    );
+                }
+
+                if options.copy_mtimes {
+                    let mtime = filetime::FileTime::from_last_modification_time(&metadata);
+                    handle!(
+                        handler,
+                        filetime::set_file_times(destination, mtime, mtime)
+                    );
+                }
+            }
 
         } else if metadata.is_dir() {
             // if this hasn't been set yet, then this must be the root of
@@ -136,11 +444,13 @@ impl OsFile for LunixFile {
 
             // we ignore the root of the new copy so we don't recursively copy
             // forever or until computer gets sad
-            } else if unique_id == root_destination.unwrap() {
+            } else if self.has_known_unique_id(&unique_id)
+                && unique_id == root_destination.unwrap() {
+
                 handle!(
                     handler,
                     Err(Error::SourceIsDestinationRoot {
-                        source: self.path.clone(),
+                        source: self.path().to_path_buf(),
                         destination: destination.to_path_buf(),
                     })
                 );
@@ -151,30 +461,34 @@ impl OsFile for LunixFile {
                 handler,
                 fs::create_dir_all(destination)
             );
+            stats.dirs += 1;
 
-            for entry in handle!(handler, fs::read_dir(&self.path)) {
+            for entry in handle!(handler, fs::read_dir(self.path())) {
                 let entry = handle!(handler, entry);
 
-                LunixFile {
-                    path: entry.path()
-                }.copy(
+                Self::from_path(entry.path()).copy(
                     &destination.join(entry.file_name()),
                     root_destination,
+                    options,
+                    stats,
+                    hard_links,
                     handler
                 );
             }
 
             // do this last just to avoid any weirdness during the copy
             // probably totally unnecessary, but why not?
-            handle!(
-                handler,
-                fs::set_permissions(destination, metadata.permissions())
-            );
+            if options.copy_permissions {
+                handle!(
+                    handler,
+                    fs::set_permissions(destination, metadata.permissions())
+                );
+            }
 
         } else {
             handle!(
                 handler,
-                Err(Error::Unknown(self.path.clone()))
+                Err(Error::Unknown(self.path().to_path_buf()))
             )
         }
     }
@@ -182,6 +496,38 @@ impl OsFile for LunixFile {
     // TODO override metadata method to cache it
 }
 
+#[cfg(unix)]
+struct UnixFile {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl OsFile for UnixFile {
+    fn from_path(path: PathBuf) -> Self {
+        UnixFile { path }
+    }
+
+    fn path(&self) -> &Path {
+        self.path.as_ref()
+    }
+}
+
+#[cfg(windows)]
+struct WindowsFile {
+    path: PathBuf,
+}
+
+#[cfg(windows)]
+impl OsFile for WindowsFile {
+    fn from_path(path: PathBuf) -> Self {
+        WindowsFile { path }
+    }
+
+    fn path(&self) -> &Path {
+        self.path.as_ref()
+    }
+}
+
 /// Copy a directory and its contents
 ///
 /// The file or directory at the source path is copied
@@ -194,13 +540,17 @@ impl OsFile for LunixFile {
 ///   operation. These errors are all returned in a `Vec`. They may or may
 ///   not be helpful or useful.
 /// * If the source path does not exist.
-/// * If the destination path exists.
+/// * If the destination path exists. Use `copy_dir_with_options` with
+///   `CopyOptions::overwrite` to merge into an existing destination
+///   instead.
 /// * If something goes wrong with copying a regular file, as with
 ///   `std::fs::copy()`.
 /// * If something goes wrong creating the new root directory when copying
 ///   a directory, as with `std::fs::create_dir()`.
-/// * If you try to copy a directory to a path prefixed by itself e.g.
-///   `copy_dir(".", "./foo")`. See below for more details.
+/// * If you try to copy a directory into itself, e.g.
+///   `copy_dir(".", "./foo")`. A pre-flight check canonicalizes the source
+///   and destination and returns `Error::DestinationInsideSource` before
+///   anything is copied, rather than recursing forever.
 ///
 /// # Caveats/Limitations
 ///
@@ -208,14 +558,6 @@ impl OsFile for LunixFile {
 /// operation are handled, but for now there is no flexibility and the following
 /// caveats and limitations apply (not by any means an exhaustive list):
 ///
-/// * You cannot currently copy a directory into itself i.e.
-///   `copy_dir(".", "./foo")`. This is because we are recursively walking
-///   the directory to be copied *while* we're copying it, so in this edge
-///   case you get an infinite recursion. Fixing this is the top of my list
-///   of things to do with this crate.
-/// * Hard links are not accounted for, i.e. if more than one hard link
-///   pointing to the same inode are to be copied, the data will be copied
-///   twice.
 /// * Filesystem boundaries may be crossed.
 /// * Symbolic links will be copied, not followed.
 pub fn copy_dir<Q, P>(from: P, to: Q) -> Result<()>
@@ -224,25 +566,334 @@ pub fn copy_dir<Q, P>(from: P, to: Q) -> Result<()>
     copy_dir_with_handler(from, to, &mut Handler::Ignore)
 }
 
+/// Basic sanity checks run before any copying happens: the source must
+/// exist, the destination must not unless an overwrite mode is set, and
+/// (since we're about to recursively
+/// walk the source) the destination must not live inside the source.
+fn preflight(from: &Path, to: &Path, overwrite: Option<Overwrite>) -> Result<()> {
+    if !from.exists() {
+        return Err(Error::SourceDoesNotExist(from.to_path_buf()));
+    }
+
+    if to.exists() && overwrite.is_none() {
+        return Err(Error::DestinationExists {
+            source: from.to_path_buf(),
+            destination: to.to_path_buf(),
+        });
+    }
+
+    let canonical_source = from.canonicalize()?;
+
+    let canonical_destination = if to.exists() {
+        to.canonicalize()?
+
+    } else {
+        let to_parent = match to.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let canonical_to_parent = to_parent.canonicalize()?;
+
+        match to.file_name() {
+            Some(name) => canonical_to_parent.join(name),
+            None => canonical_to_parent,
+        }
+    };
+
+    if canonical_destination == canonical_source
+        || canonical_destination.starts_with(&canonical_source) {
+
+        return Err(Error::DestinationInsideSource {
+            source: canonical_source,
+            destination: canonical_destination,
+        });
+    }
+
+    Ok(())
+}
+
 /// Same as copy_dir, but allows clients to specify a `Handler` for any errors
-/// that occur. 
+/// that occur.
 pub fn copy_dir_with_handler<Q, P>(from: P, to: Q,
                                    handler: &mut Handler) -> Result<()>
     where Q: AsRef<Path>, P: AsRef<Path> {
 
-    if !from.as_ref().exists() {
-        return Err(Error::SourceDoesNotExist(from.as_ref().to_path_buf()));
+    copy_dir_with_options(from, to, &mut CopyOptions::new(), handler)
+        .map( |_stats| () )
+}
 
-    } else if to.as_ref().exists() {
-        return Err(Error::DestinationExists {
-            source: from.as_ref().to_path_buf(),
-            destination: to.as_ref().to_path_buf(),
-        });
+/// Same as `copy_dir_with_handler`, but allows clients to specify a
+/// `CopyOptions` to filter entries and control what metadata is preserved.
+/// Returns a `CopyStats` summarizing the files, directories and bytes
+/// copied.
+pub fn copy_dir_with_options<Q, P>(from: P, to: Q,
+                                   options: &mut CopyOptions,
+                                   handler: &mut Handler) -> Result<CopyStats>
+    where Q: AsRef<Path>, P: AsRef<Path> {
+
+    preflight(from.as_ref(), to.as_ref(), options.overwrite)?;
+
+    match options.parallel {
+        Some(n) => Ok(copy_dir_parallel(from.as_ref(), to.as_ref(), n, options, handler)),
+        None => {
+            let mut stats = CopyStats::new();
+            let mut hard_links = HashMap::new();
+            let source = new_os_file(&from);
+            source.copy(to.as_ref(), None, options, &mut stats, &mut hard_links, handler);
+            Ok(stats)
+        }
     }
+}
 
-    let source = new_os_file(&from);
-    source.copy(to.as_ref(), None, handler);
-    Ok(())
+/// A single independent file copy discovered while walking the source tree
+/// for a parallel copy. Carries the metadata needed to apply
+/// `copy_permissions`/`copy_mtimes` and to count the job correctly in
+/// `CopyStats`, since the worker thread that performs the actual
+/// `fs::copy` has no other access to the source's metadata.
+struct FileJob {
+    source: PathBuf,
+    destination: PathBuf,
+    metadata: fs::Metadata,
+    is_symlink: bool,
+}
+
+/// A hard link discovered while walking the source tree for a parallel
+/// copy. Unlike the sequential walk, we can't call `fs::hard_link` during
+/// the walk itself: `existing` is another file's *destination*, and that
+/// file hasn't been copied yet (all `FileJob`s run later, on the worker
+/// pool). So we record the link to create once every `FileJob` has been
+/// copied, and keep `source`/`metadata` around to fall back to an
+/// independent copy if the link still fails for some other reason.
+struct LinkJob {
+    existing: PathBuf,
+    source: PathBuf,
+    destination: PathBuf,
+    metadata: fs::Metadata,
+}
+
+/// Apply `copy_permissions`/`copy_mtimes` to a just-copied `destination`,
+/// returning any errors encountered rather than reporting them directly,
+/// so callers on both sides of the parallel walk (the worker pool, which
+/// can only reach the `Handler` through a shared error vector, and the
+/// sequential hard-link fallback) can route them appropriately.
+fn apply_copied_file_options(destination: &Path,
+                              metadata: &fs::Metadata,
+                              copy_permissions: bool,
+                              copy_mtimes: bool) -> Vec<Error> {
+
+    let mut errors = Vec::new();
+
+    if copy_permissions {
+        if let Err(err) = fs::set_permissions(destination, metadata.permissions()) {
+            errors.push(Error::from(err));
+        }
+    }
+
+    if copy_mtimes {
+        let mtime = filetime::FileTime::from_last_modification_time(metadata);
+        if let Err(err) = filetime::set_file_times(destination, mtime, mtime) {
+            errors.push(Error::from(err));
+        }
+    }
+
+    errors
+}
+
+/// Walk the source tree, recording the directory skeleton (in creation
+/// order), the independent file copy jobs it contains, and any hard links
+/// to create once those copies are done. Errors are routed through
+/// `handler` exactly like `OsFile::copy`: a failure aborts only the walk
+/// of the entry it occurred on (and anything below it), not the whole
+/// operation.
+fn walk_for_parallel_copy(source: &Path,
+                          destination: &Path,
+                          options: &mut CopyOptions,
+                          dirs: &mut Vec<(PathBuf, fs::Metadata)>,
+                          files: &mut Vec<FileJob>,
+                          links: &mut Vec<LinkJob>,
+                          hard_links: &mut HashMap<UniqueId, PathBuf>,
+                          stats: &mut CopyStats,
+                          handler: &mut Handler) {
+
+    let metadata = handle!(handler, fs::metadata(source));
+
+    if !options.should_copy(source, &metadata) {
+        return
+    }
+
+    if metadata.is_dir() {
+        dirs.push((destination.to_path_buf(), metadata));
+
+        for entry in handle!(handler, fs::read_dir(source)) {
+            let entry = handle!(handler, entry);
+            walk_for_parallel_copy(
+                &entry.path(),
+                &destination.join(entry.file_name()),
+                options,
+                dirs,
+                files,
+                links,
+                hard_links,
+                stats,
+                handler,
+            );
+        }
+
+    } else if metadata.is_file() {
+        let is_symlink = fs::symlink_metadata(source)
+            .map( |m| m.file_type().is_symlink() )
+            .unwrap_or(false);
+
+        let destination_exists = destination.exists();
+
+        if !should_write_file(destination, &metadata, options.overwrite) {
+            return
+        }
+
+        if destination_exists {
+            handle!(handler, fs::remove_file(destination));
+        }
+
+        let unique_id = unique_id_from_metadata(&metadata);
+        let eligible = options.preserve_hard_links && !is_symlink
+            && unique_id_is_known(&unique_id);
+
+        match plan_hard_link(destination, unique_id, eligible, hard_links) {
+            HardLinkPlan::Copy => {
+                files.push(FileJob {
+                    source: source.to_path_buf(),
+                    destination: destination.to_path_buf(),
+                    metadata,
+                    is_symlink,
+                });
+            },
+            HardLinkPlan::LinkTo(existing) => {
+                links.push(LinkJob {
+                    existing,
+                    source: source.to_path_buf(),
+                    destination: destination.to_path_buf(),
+                    metadata,
+                });
+            },
+        }
+
+    } else {
+        handle!(handler, Err(Error::Unknown(source.to_path_buf())));
+    }
+}
+
+/// Copy `from` to `to` using `n` worker threads for the independent file
+/// copies. The directory skeleton is created, and directory permissions
+/// applied, sequentially so the layout is always consistent; only the
+/// `fs::copy` calls for regular files are dispatched across the pool.
+/// Errors encountered along the way are reported through `handler`, same
+/// as the sequential path, rather than aborting the whole copy.
+fn copy_dir_parallel(from: &Path,
+                     to: &Path,
+                     n: usize,
+                     options: &mut CopyOptions,
+                     handler: &mut Handler) -> CopyStats {
+
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut links = Vec::new();
+    let mut hard_links = HashMap::new();
+    let mut stats = CopyStats::new();
+    walk_for_parallel_copy(
+        from, to, options, &mut dirs, &mut files, &mut links, &mut hard_links, &mut stats,
+        handler);
+
+    for (destination, _) in &dirs {
+        match fs::create_dir_all(destination) {
+            Ok(()) => stats.dirs += 1,
+            Err(err) => handler.handle(Error::from(err)),
+        }
+    }
+
+    let jobs = Arc::new(Mutex::new(files));
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let shared_stats = Arc::new(Mutex::new(CopyStats::new()));
+    let copy_permissions = options.copy_permissions;
+    let copy_mtimes = options.copy_mtimes;
+
+    let handles: Vec<_> = (0..std::cmp::max(1, n)).map( |_| {
+        let jobs = jobs.clone();
+        let errors = errors.clone();
+        let shared_stats = shared_stats.clone();
+
+        thread::spawn(move || {
+            loop {
+                let job = match jobs.lock().unwrap().pop() {
+                    Some(job) => job,
+                    None => break,
+                };
+
+                match fs::copy(&job.source, &job.destination) {
+                    Ok(bytes) => {
+                        for err in apply_copied_file_options(
+                            &job.destination, &job.metadata, copy_permissions, copy_mtimes) {
+                            errors.lock().unwrap().push(err);
+                        }
+
+                        let mut stats = shared_stats.lock().unwrap();
+                        if job.is_symlink {
+                            stats.symlinks += 1;
+                        } else {
+                            stats.files += 1;
+                        }
+                        stats.bytes += bytes;
+                    },
+                    Err(err) => errors.lock().unwrap().push(Error::from(err)),
+                }
+            }
+        })
+    }).collect();
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    // Only now, with every FileJob's worker thread finished, is it safe to
+    // create these hard links: each one points at another file's
+    // destination, which is guaranteed to exist on disk at this point.
+    for link_job in links {
+        if fs::hard_link(&link_job.existing, &link_job.destination).is_ok() {
+            stats.files += 1;
+        } else {
+            match fs::copy(&link_job.source, &link_job.destination) {
+                Ok(bytes) => {
+                    stats.files += 1;
+                    stats.bytes += bytes;
+
+                    for err in apply_copied_file_options(
+                        &link_job.destination, &link_job.metadata,
+                        options.copy_permissions, options.copy_mtimes) {
+                        handler.handle(err);
+                    }
+                },
+                Err(err) => handler.handle(Error::from(err)),
+            }
+        }
+    }
+
+    if options.copy_permissions {
+        for (destination, metadata) in dirs.iter().rev() {
+            if let Err(err) = fs::set_permissions(destination, metadata.permissions()) {
+                handler.handle(Error::from(err));
+            }
+        }
+    }
+
+    for error in Arc::try_unwrap(errors).unwrap().into_inner().unwrap() {
+        handler.handle(error);
+    }
+
+    let shared_stats = Arc::try_unwrap(shared_stats).unwrap().into_inner().unwrap();
+    stats.files += shared_stats.files;
+    stats.symlinks += shared_stats.symlinks;
+    stats.bytes += shared_stats.bytes;
+
+    stats
 }
 
 #[cfg(test)]
@@ -331,7 +982,248 @@ mod tests {
         let from = base_dir.as_ref().join("foo");
         let to = from.as_path().join("beez");
 
-        let copy_result = super::copy_dir(&from, &to).unwrap();
+        match super::copy_dir(&from, &to) {
+            Ok(_) => panic!("expected Err"),
+            Err(err) => match err {
+                Error::DestinationInsideSource { .. } => (),
+                _ => panic!("expected DestinationInsideSource"),
+            },
+        }
+    }
+
+    #[test]
+    fn filter_excludes_matching_entries() {
+        use super::CopyOptions;
+
+        let base_dir = TempDir::new("copy_dir_test").unwrap();
+
+        let source_dir = base_dir.as_ref().join("source");
+        fs::create_dir(&source_dir).unwrap();
+        fs::write(source_dir.join("keep"), b"keep me").unwrap();
+        fs::write(source_dir.join("skip"), b"skip me").unwrap();
+
+        let destination_dir = base_dir.as_ref().join("dest");
+
+        super::copy_dir_with_options(
+            &source_dir,
+            &destination_dir,
+            &mut CopyOptions::new().filter( |path, _metadata| {
+                path.file_name().map_or(true, |name| name != "skip")
+            }),
+            &mut super::Handler::Ignore,
+        ).unwrap();
+
+        assert!(destination_dir.join("keep").exists());
+        assert!(!destination_dir.join("skip").exists());
+    }
+
+    #[test]
+    fn copy_stats_reports_files_dirs_and_bytes() {
+        use super::CopyOptions;
+
+        let base_dir = TempDir::new("copy_dir_test").unwrap();
+
+        let source_dir = base_dir.as_ref().join("source");
+        fs::create_dir(&source_dir).unwrap();
+        fs::write(source_dir.join("a"), b"1234").unwrap();
+        fs::create_dir(source_dir.join("sub")).unwrap();
+        fs::write(source_dir.join("sub").join("b"), b"12345678").unwrap();
+
+        let destination_dir = base_dir.as_ref().join("dest");
+
+        let stats = super::copy_dir_with_options(
+            &source_dir,
+            &destination_dir,
+            &mut CopyOptions::new(),
+            &mut super::Handler::Ignore,
+        ).unwrap();
+
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.dirs, 2);
+        assert_eq!(stats.bytes, 12);
+        assert_eq!(stats.symlinks, 0);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn preserves_hard_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let base_dir = TempDir::new("copy_dir_test").unwrap();
+
+        let source_dir = base_dir.as_ref().join("source");
+        fs::create_dir(&source_dir).unwrap();
+
+        let original = source_dir.join("original");
+        fs::write(&original, b"shared data").unwrap();
+
+        let linked = source_dir.join("linked");
+        fs::hard_link(&original, &linked).unwrap();
+
+        let destination_dir = base_dir.as_ref().join("dest");
+        super::copy_dir(&source_dir, &destination_dir).unwrap();
+
+        let original_ino = fs::metadata(destination_dir.join("original")).unwrap().ino();
+        let linked_ino = fs::metadata(destination_dir.join("linked")).unwrap().ino();
+
+        assert_eq!(original_ino, linked_ino);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn parallel_copy_applies_mtimes_and_counts_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let base_dir = TempDir::new("copy_dir_test").unwrap();
+
+        let source_dir = base_dir.as_ref().join("source");
+        fs::create_dir(&source_dir).unwrap();
+
+        let original = source_dir.join("original");
+        fs::write(&original, b"file data").unwrap();
+
+        let old_mtime = filetime::FileTime::from_unix_time(1000000000, 0);
+        filetime::set_file_times(&original, old_mtime, old_mtime).unwrap();
+
+        symlink("original", source_dir.join("link")).unwrap();
+
+        let destination_dir = base_dir.as_ref().join("dest");
+
+        let stats = super::copy_dir_with_options(
+            &source_dir,
+            &destination_dir,
+            &mut super::CopyOptions::new()
+                .parallel(2)
+                .preserve_hard_links(false)
+                .copy_mtimes(true),
+            &mut super::Handler::Ignore,
+        ).unwrap();
+
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.symlinks, 1);
+
+        let copied_mtime = filetime::FileTime::from_last_modification_time(
+            &fs::metadata(destination_dir.join("original")).unwrap()
+        );
+        assert_eq!(copied_mtime, old_mtime);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn parallel_copy_preserves_hard_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let base_dir = TempDir::new("copy_dir_test").unwrap();
+
+        let source_dir = base_dir.as_ref().join("source");
+        fs::create_dir(&source_dir).unwrap();
+
+        let original = source_dir.join("original");
+        fs::write(&original, b"shared data").unwrap();
+
+        let linked = source_dir.join("linked");
+        fs::hard_link(&original, &linked).unwrap();
+
+        let destination_dir = base_dir.as_ref().join("dest");
+
+        super::copy_dir_with_options(
+            &source_dir,
+            &destination_dir,
+            &mut super::CopyOptions::new().parallel(2),
+            &mut super::Handler::Ignore,
+        ).unwrap();
+
+        let original_ino = fs::metadata(destination_dir.join("original")).unwrap().ino();
+        let linked_ino = fs::metadata(destination_dir.join("linked")).unwrap().ino();
+
+        assert_eq!(original_ino, linked_ino);
+    }
+
+    #[test]
+    fn overwrite_merges_into_existing_destination() {
+        use super::{CopyOptions, Overwrite};
+
+        let base_dir = TempDir::new("copy_dir_test").unwrap();
+
+        let source_dir = base_dir.as_ref().join("source");
+        fs::create_dir(&source_dir).unwrap();
+        fs::write(source_dir.join("keep"), b"source version").unwrap();
+        fs::write(source_dir.join("fresh"), b"new file").unwrap();
+
+        let destination_dir = base_dir.as_ref().join("dest");
+        fs::create_dir(&destination_dir).unwrap();
+        fs::write(destination_dir.join("keep"), b"destination version").unwrap();
+
+        super::copy_dir_with_options(
+            &source_dir,
+            &destination_dir,
+            &mut CopyOptions::new().overwrite(Overwrite::Never),
+            &mut super::Handler::Ignore,
+        ).unwrap();
+
+        assert_eq!(
+            fs::read(destination_dir.join("keep")).unwrap(),
+            b"destination version"
+        );
+        assert_eq!(
+            fs::read(destination_dir.join("fresh")).unwrap(),
+            b"new file"
+        );
+
+        super::copy_dir_with_options(
+            &source_dir,
+            &destination_dir,
+            &mut CopyOptions::new().overwrite(Overwrite::Always),
+            &mut super::Handler::Ignore,
+        ).unwrap();
+
+        assert_eq!(
+            fs::read(destination_dir.join("keep")).unwrap(),
+            b"source version"
+        );
+    }
+
+    #[test]
+    fn overwrite_if_newer_respects_mtimes() {
+        use super::{CopyOptions, Overwrite};
+
+        let base_dir = TempDir::new("copy_dir_test").unwrap();
+
+        let source_dir = base_dir.as_ref().join("source");
+        fs::create_dir(&source_dir).unwrap();
+        let source_file = source_dir.join("file");
+        fs::write(&source_file, b"source version").unwrap();
+
+        let destination_dir = base_dir.as_ref().join("dest");
+        fs::create_dir(&destination_dir).unwrap();
+        let destination_file = destination_dir.join("file");
+        fs::write(&destination_file, b"destination version").unwrap();
+
+        let older = filetime::FileTime::from_unix_time(1000000000, 0);
+        let newer = filetime::FileTime::from_unix_time(2000000000, 0);
+
+        filetime::set_file_times(&source_file, older, older).unwrap();
+        filetime::set_file_times(&destination_file, newer, newer).unwrap();
+
+        super::copy_dir_with_options(
+            &source_dir,
+            &destination_dir,
+            &mut CopyOptions::new().overwrite(Overwrite::IfNewer),
+            &mut super::Handler::Ignore,
+        ).unwrap();
+
+        assert_eq!(fs::read(&destination_file).unwrap(), b"destination version");
+
+        filetime::set_file_times(&source_file, newer, newer).unwrap();
+
+        super::copy_dir_with_options(
+            &source_dir,
+            &destination_dir,
+            &mut CopyOptions::new().overwrite(Overwrite::IfNewer),
+            &mut super::Handler::Ignore,
+        ).unwrap();
+
+        assert_eq!(fs::read(&destination_file).unwrap(), b"source version");
     }
 
     fn assert_dirs_same<P: AsRef<Path>>(a: P, b: P) {